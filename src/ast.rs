@@ -0,0 +1,206 @@
+//! Markdown 到 LaTeX 转换的中间表示：先把 `pulldown_cmark` 的事件流折叠成一棵
+//! 带类型的节点树，再交给 `render` 模块单独走一遍渲染。事件流本身是扁平的，
+//! 容器类节点（标题、列表、表格……）都靠一个通用的栈来配对 Start/End。
+
+use pulldown_cmark::{Alignment, CodeBlockKind, Event, HeadingLevel, Options, Parser as MdParser, Tag};
+use regex::Regex;
+use std::sync::LazyLock;
+
+/// 围栏语言标记中 `block_equation{name}` 标签的匹配规则
+static EQUATION_FENCE_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"block_equation\{(.*?)\}").unwrap());
+
+/// 文档的中间表示节点。块级与行内节点统一放在同一棵树里，容器节点的 `children`
+/// 就是其内部事件流折叠出的子节点列表。
+pub enum Node {
+    Heading {
+        level: HeadingLevel,
+        classes: Vec<String>,
+        children: Vec<Node>,
+    },
+    Paragraph(Vec<Node>),
+    List {
+        ordered: bool,
+        items: Vec<Node>,
+    },
+    /// 只会出现在 `List::items` 里
+    ListItem {
+        task: Option<bool>,
+        children: Vec<Node>,
+    },
+    Table {
+        alignments: Vec<Alignment>,
+        rows: Vec<Node>,
+    },
+    /// 只会出现在 `Table::rows` 里
+    TableRow(Vec<Node>),
+    /// 只会出现在 `TableRow` 里
+    TableCell(Vec<Node>),
+    CodeBlock {
+        lang: Option<String>,
+        body: String,
+    },
+    Equation {
+        label: Option<String>,
+        body: String,
+    },
+    RawLatex {
+        body: String,
+    },
+    Image {
+        url: String,
+        caption: Vec<Node>,
+    },
+    BlockQuote(Vec<Node>),
+    FootnoteDefinition {
+        label: String,
+        children: Vec<Node>,
+    },
+    FootnoteReference(String),
+    Link {
+        url: String,
+        children: Vec<Node>,
+    },
+    Emphasis(Vec<Node>),
+    Strong(Vec<Node>),
+    Strikethrough(Vec<Node>),
+    Text(String),
+    Code(String),
+    /// 只会出现在 `ListItem::children` 的开头，折叠时会被提取进 `ListItem::task`
+    TaskMarker(bool),
+    Rule,
+    SoftBreak,
+    HardBreak,
+}
+
+/// 把 Markdown 源码折叠为一棵 `Node` 树，供 `render::render_document` 消费
+pub fn build_ast(markdown: &str) -> Vec<Node> {
+    let parser = MdParser::new_ext(markdown, Options::all());
+
+    let mut root: Vec<Node> = Vec::new();
+    // 每一层未闭合的 Start 标签对应一个 frame：(标签本身, 已折叠的子节点, 代码块原始文本)
+    let mut stack: Vec<(Tag<'_>, Vec<Node>, String)> = Vec::new();
+
+    for event in parser {
+        match event {
+            Event::Start(tag) => stack.push((tag, Vec::new(), String::new())),
+            Event::End(tag) => {
+                if let Some((_, children, code_buffer)) = stack.pop() {
+                    let node = finish_node(tag, children, code_buffer);
+                    push_node(&mut stack, &mut root, node);
+                }
+            }
+            Event::Text(text) => {
+                if let Some(frame) = stack.last_mut() {
+                    if matches!(frame.0, Tag::CodeBlock(_)) {
+                        frame.2.push_str(&text);
+                    } else {
+                        frame.1.push(Node::Text(text.to_string()));
+                    }
+                } else {
+                    root.push(Node::Text(text.to_string()));
+                }
+            }
+            Event::Code(code) => push_node(&mut stack, &mut root, Node::Code(code.to_string())),
+            Event::Rule => push_node(&mut stack, &mut root, Node::Rule),
+            Event::SoftBreak => push_node(&mut stack, &mut root, Node::SoftBreak),
+            Event::HardBreak => push_node(&mut stack, &mut root, Node::HardBreak),
+            Event::TaskListMarker(checked) => {
+                push_node(&mut stack, &mut root, Node::TaskMarker(checked))
+            }
+            Event::FootnoteReference(label) => {
+                push_node(&mut stack, &mut root, Node::FootnoteReference(label.to_string()))
+            }
+            _ => {}
+        }
+    }
+
+    root
+}
+
+fn push_node(stack: &mut [(Tag<'_>, Vec<Node>, String)], root: &mut Vec<Node>, node: Node) {
+    if let Some(frame) = stack.last_mut() {
+        frame.1.push(node);
+    } else {
+        root.push(node);
+    }
+}
+
+/// 根据已关闭的标签类型，把累积的子节点/文本组装成对应的 `Node`
+fn finish_node(tag: Tag<'_>, children: Vec<Node>, code_buffer: String) -> Node {
+    match tag {
+        Tag::Heading(level, _, classes) => Node::Heading {
+            level,
+            classes: classes.iter().map(|c| c.to_string()).collect(),
+            children,
+        },
+        Tag::Paragraph => Node::Paragraph(children),
+        Tag::Emphasis => Node::Emphasis(children),
+        Tag::Strong => Node::Strong(children),
+        Tag::Strikethrough => Node::Strikethrough(children),
+        Tag::Link(_, url, _) => Node::Link {
+            url: url.to_string(),
+            children,
+        },
+        Tag::Image(_, url, _) => Node::Image {
+            url: url.to_string(),
+            caption: children,
+        },
+        Tag::BlockQuote => Node::BlockQuote(children),
+        Tag::Item => {
+            let mut children = children;
+            let task = match children.first() {
+                Some(Node::TaskMarker(_)) => match children.remove(0) {
+                    Node::TaskMarker(checked) => Some(checked),
+                    _ => unreachable!(),
+                },
+                _ => None,
+            };
+            Node::ListItem { task, children }
+        }
+        Tag::List(ordered) => Node::List {
+            ordered: ordered.is_some(),
+            items: children,
+        },
+        Tag::TableCell => Node::TableCell(children),
+        Tag::TableHead | Tag::TableRow => Node::TableRow(children),
+        Tag::Table(alignments) => Node::Table {
+            alignments,
+            rows: children,
+        },
+        Tag::CodeBlock(kind) => classify_code_block(kind, code_buffer),
+        Tag::FootnoteDefinition(label) => Node::FootnoteDefinition {
+            label: label.to_string(),
+            children,
+        },
+        // 兜底：pulldown_cmark 未来若新增标签类型，至少把内容原样保留下来
+        #[allow(unreachable_patterns)]
+        _ => Node::Paragraph(children),
+    }
+}
+
+/// 围栏代码块按语言标记分类为公式块、原样输出的 LaTeX 或普通代码块
+fn classify_code_block(kind: CodeBlockKind<'_>, body: String) -> Node {
+    let CodeBlockKind::Fenced(lang) = &kind else {
+        return Node::CodeBlock { lang: None, body };
+    };
+
+    let tags: Vec<&str> = lang.split(' ').filter(|s| !s.is_empty()).collect();
+    for tag in &tags {
+        if let Some(caps) = EQUATION_FENCE_RE.captures(tag) {
+            let label = caps[1].to_string();
+            return Node::Equation {
+                label: if label.is_empty() { None } else { Some(label) },
+                body,
+            };
+        }
+    }
+    if tags.contains(&"latex") && tags.contains(&"raw") {
+        return Node::RawLatex { body };
+    }
+
+    Node::CodeBlock {
+        lang: Some(lang.to_string()),
+        body,
+    }
+}
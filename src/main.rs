@@ -1,18 +1,26 @@
 use clap::Parser;
-use pulldown_cmark::{
-    CodeBlockKind, CowStr, Event, HeadingLevel, Options, Parser as MdParser, Tag,
-};
 use regex::Regex;
-use std::{borrow::Cow, collections::HashMap, path::Path, sync::LazyLock};
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    path::Path,
+    sync::LazyLock,
+};
 
-static PRE_REPLACEMENT_TABLE: LazyLock<HashMap<&'static str, &'static str>> = LazyLock::new(|| {
-    HashMap::from([
-        ("[`", "\\cite{"),
-        ("`]", "}"),
-        ("[*", "\\ref{"),
-        ("*]", "}"),
-    ])
-});
+mod ast;
+mod render;
+
+/// 匹配 `` [`key`] `` 形式的引文
+static CITATION_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\[`([^`]*)`\]").unwrap());
+/// 匹配 `[*key*]` 形式的交叉引用
+static REFERENCE_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\[\*([^*]*)\*\]").unwrap());
+/// `postprocess` 中用于回填 `\cite{}`/`\ref{}` 的标记，两端使用私有区字符包裹，
+/// 不会与正文冲突，也不会被 `IN_TEXT_REPLACEMENT_TABLE` 转义
+static REF_MARKER_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new("\u{E000}([CR])([^\u{E000}]*)\u{E000}").unwrap());
+/// 匹配引用块首行的 `[!NOTE]` 风格 callout 标记
+static ADMONITION_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?s)^\[!(\w+)\]\s*(.*)$").unwrap());
 
 static IN_TEXT_REPLACEMENT_TABLE: LazyLock<HashMap<&'static str, &'static str>> =
     LazyLock::new(|| {
@@ -26,6 +34,119 @@ static IN_TEXT_REPLACEMENT_TABLE: LazyLock<HashMap<&'static str, &'static str>>
         ])
     });
 
+/// 默认的文档前导，声明了转换结果依赖的全部宏包
+const LATEX_HEADER: &str = r#"\documentclass[11pt]{article}
+\usepackage{graphicx}
+\usepackage{hyperref}
+\usepackage{listings}
+\usepackage{xcolor}
+\usepackage{tabularx}
+\usepackage{alltt}
+\usepackage{tcolorbox}
+\usepackage{ulem}
+\usepackage{amssymb}
+
+\definecolor{codebg}{rgb}{0.95,0.95,0.92}
+\definecolor{codekeyword}{rgb}{0.00,0.00,0.60}
+\definecolor{codecomment}{rgb}{0.25,0.50,0.25}
+\definecolor{codestring}{rgb}{0.60,0.00,0.00}
+
+\lstset{
+    backgroundcolor=\color{codebg},
+    keywordstyle=\color{codekeyword}\bfseries,
+    commentstyle=\color{codecomment}\itshape,
+    stringstyle=\color{codestring},
+    basicstyle=\ttfamily\small,
+    showstringspaces=false,
+    breaklines=true,
+}
+
+\begin{document}
+"#;
+
+/// 默认的文档收尾
+const LATEX_FOOTER: &str = r#"
+\end{document}
+"#;
+
+/// `listings` 宏包原生认识的语言名（围栏语言标记 -> `language=` 选项值）
+static LISTINGS_LANGUAGES: LazyLock<HashMap<&'static str, &'static str>> = LazyLock::new(|| {
+    HashMap::from([
+        ("c", "C"),
+        ("cpp", "C++"),
+        ("c++", "C++"),
+        ("java", "Java"),
+        ("python", "Python"),
+        ("py", "Python"),
+        ("bash", "bash"),
+        ("sh", "bash"),
+        ("sql", "SQL"),
+        ("html", "HTML"),
+        ("xml", "XML"),
+        ("make", "make"),
+        ("tex", "TeX"),
+    ])
+});
+
+/// 代码块高亮方式
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HighlightMode {
+    /// 使用 `listings` 的 `language=` 选项（未知语言自动回退到 syntect）
+    Listings,
+    /// 始终使用 syntect 逐词上色
+    Syntect,
+    /// 不做任何高亮，沿用纯 `lstlisting`
+    None,
+}
+
+/// 引文/交叉引用 key 校验失败时返回的错误
+#[derive(Debug)]
+pub enum ConvertError {
+    /// `` [`key`] `` 或 `[*key*]` 中 key 为空
+    EmptyKey { kind: &'static str },
+    /// key 中包含空白、控制字符，或 `-`/`:` 以外的标点
+    InvalidChar {
+        kind: &'static str,
+        key: String,
+        offending_char: char,
+    },
+}
+
+impl std::fmt::Display for ConvertError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConvertError::EmptyKey { kind } => write!(f, "{kind} 的 key 不能为空"),
+            ConvertError::InvalidChar {
+                kind,
+                key,
+                offending_char,
+            } => write!(
+                f,
+                "{kind} 的 key `{key}` 含有非法字符 `{offending_char}`（仅允许字母、数字、`-`、`:`）"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConvertError {}
+
+/// 校验引文/交叉引用的 key：禁止空名、空白、控制字符，以及 `-`/`:` 以外的标点
+fn validate_key(kind: &'static str, key: &str) -> Result<(), ConvertError> {
+    if key.is_empty() {
+        return Err(ConvertError::EmptyKey { kind });
+    }
+    for c in key.chars() {
+        if !(c.is_alphanumeric() || c == '-' || c == ':') {
+            return Err(ConvertError::InvalidChar {
+                kind,
+                key: key.to_string(),
+                offending_char: c,
+            });
+        }
+    }
+    Ok(())
+}
+
 /// 将 Markdown 文件中的内容转换为 LaTeX（支持语法映射与公式块）
 #[derive(Parser, Debug)]
 #[command(author, version, about)]
@@ -37,250 +158,237 @@ pub struct Args {
     /// 输出的 LaTeX 文件路径（可选，默认替换 .md 为 .tex）
     #[arg(value_name = "OUTPUT")]
     pub output: Option<String>,
+
+    /// 将转换结果包裹为完整的 LaTeX 文档（\documentclass ... \begin{document} ... \end{document}）
+    #[arg(long)]
+    pub standalone: bool,
+
+    /// 使用 tectonic 直接编译为 PDF（隐含 --standalone）；未指定 OUTPUT 时默认输出为同名 .pdf，
+    /// 指定了 OUTPUT 时按原路径写入
+    #[arg(long)]
+    pub pdf: bool,
+
+    /// 覆盖默认前导/收尾的模板文件，文件内容需包含 `{{BODY}}` 占位符；若模板中
+    /// `{{BODY}}` 之前的部分不包含字面量 `\begin{document}`，标题/作者无法被插入
+    #[arg(long, value_name = "FILE")]
+    pub preamble: Option<String>,
+
+    /// 代码块高亮方式
+    #[arg(long, value_enum, default_value_t = HighlightMode::Listings)]
+    pub highlight: HighlightMode,
+
+    /// 将文档的第一个一级标题提取为 \title，而不是渲染成 \chapter。仅在 --standalone/--pdf
+    /// 下生效，且 front matter 已给出 `title:` 时不提取
+    #[arg(long)]
+    pub extract_title: bool,
+
+    /// 文档作者，写入 \author{}（也可以在 YAML front matter 里用 `author:` 指定，命令行优先）
+    #[arg(long)]
+    pub author: Option<String>,
 }
 
 fn main() -> std::io::Result<()> {
     let args = Args::parse();
 
     let input_path = args.input;
+    let standalone = args.standalone || args.pdf;
     let output_path = args.output.unwrap_or_else(|| {
         let path = Path::new(&input_path);
         let stem = path.file_stem().unwrap_or_default().to_string_lossy();
         let parent = path.parent().unwrap_or_else(|| Path::new(""));
+        let ext = if args.pdf { "pdf" } else { "tex" };
         parent
-            .join(format!("{stem}.tex"))
+            .join(format!("{stem}.{ext}"))
             .to_string_lossy()
             .into_owned()
     });
 
     let md_content = std::fs::read_to_string(&input_path)?;
-    let latex = convert_markdown_to_latex(&md_content);
-    std::fs::write(&output_path, latex)?;
+    let (front_title, front_author, md_body) = extract_front_matter(&md_content);
+    // 标题/作者最终都会被裸插入 \title{}/\author{}，提前转义一次，避免重复转义
+    let author = args
+        .author
+        .clone()
+        .or(front_author)
+        .map(|a| apply_text_replacements(&a, &IN_TEXT_REPLACEMENT_TABLE));
+    let front_title = front_title.map(|t| apply_text_replacements(&t, &IN_TEXT_REPLACEMENT_TABLE));
+    // `--extract-title` 只在生成完整文档时才有意义：非 standalone 模式下正文里
+    // 没有地方可以写入提取出的标题，继续抽取只会把这个一级标题整个丢掉
+    let extract_title_from_heading = args.extract_title && standalone && front_title.is_none();
+
+    let converted = convert_markdown_to_latex(&md_body, args.highlight, extract_title_from_heading)
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err.to_string()))?;
+    let title = front_title.or(converted.title);
+
+    let document = if standalone {
+        let (mut header, footer) = match &args.preamble {
+            Some(path) => {
+                let template = std::fs::read_to_string(path)?;
+                split_preamble_template(&template)
+            }
+            None => (LATEX_HEADER.to_string(), LATEX_FOOTER.to_string()),
+        };
+        let body = if let Some(title) = &title {
+            if !header.contains("\\begin{document}") {
+                eprintln!(
+                    "warning: --preamble 模板中没有找到 \\begin{{document}}，\\title{{}}/\\author{{}} 不会被写入"
+                );
+            }
+            header = header.replacen(
+                "\\begin{document}",
+                &format!(
+                    "\\title{{{}}}\n\\author{{{}}}\n\\begin{{document}}",
+                    title,
+                    author.as_deref().unwrap_or("")
+                ),
+                1,
+            );
+            format!("\\maketitle\n\n{}", converted.body)
+        } else {
+            converted.body
+        };
+        format!("{header}{body}{footer}")
+    } else {
+        converted.body
+    };
+
+    if args.pdf {
+        let pdf_bytes = tectonic::latex_to_pdf(&document).map_err(|err| {
+            std::io::Error::new(std::io::ErrorKind::Other, format!("tectonic 编译失败: {err}"))
+        })?;
+        std::fs::write(&output_path, pdf_bytes)?;
+    } else {
+        std::fs::write(&output_path, document)?;
+    }
 
     Ok(())
 }
 
-#[derive(PartialEq, Eq)]
-enum CustomCodeBlockKind<'a> {
-    None,
-    Code(Option<CowStr<'a>>),
-    RawLatex,
-    Equation,
+/// 解析开头的 YAML front matter（`---` 包裹），提取 `title`/`author`，返回去掉 front matter 的正文
+fn extract_front_matter(markdown: &str) -> (Option<String>, Option<String>, String) {
+    let mut lines = markdown.lines();
+    let Some(first) = lines.next() else {
+        return (None, None, markdown.to_string());
+    };
+    if first.trim() != "---" {
+        return (None, None, markdown.to_string());
+    }
+
+    let mut title = None;
+    let mut author = None;
+    let mut closed = false;
+    for line in lines.by_ref() {
+        if line.trim() == "---" {
+            closed = true;
+            break;
+        }
+        if let Some((key, value)) = line.split_once(':') {
+            let value = value.trim().trim_matches('"').to_string();
+            match key.trim() {
+                "title" => title = Some(value),
+                "author" => author = Some(value),
+                _ => {}
+            }
+        }
+    }
+
+    if !closed {
+        return (None, None, markdown.to_string());
+    }
+
+    let mut body = lines.collect::<Vec<_>>().join("\n");
+    if markdown.ends_with('\n') && !body.is_empty() {
+        body.push('\n');
+    }
+    (title, author, body)
 }
 
-fn convert_markdown_to_latex(markdown: &str) -> String {
-    let preprocessed = preprocess(markdown);
+/// 从自定义模板中切出前导与收尾部分，模板必须包含一个 `{{BODY}}` 占位符；
+/// 前导部分还必须包含字面量 `\begin{document}`，否则标题/作者无处插入（见 main 中的提醒）
+fn split_preamble_template(template: &str) -> (String, String) {
+    match template.split_once("{{BODY}}") {
+        Some((header, footer)) => (header.to_string(), footer.to_string()),
+        None => (template.to_string(), String::new()),
+    }
+}
 
-    let mut output = String::new();
-    let parser = MdParser::new_ext(&preprocessed, Options::all());
-
-    let mut inside_image = false;
-    let mut image_url = String::new();
-    let mut image_caption = String::new();
-    let mut _inside_paragraph = false;
-    let mut _in_ordered_list = false;
-    let mut _in_unordered_list = false;
-    let mut codeblock_status = CustomCodeBlockKind::None;
-
-    let mut _inside_header = false;
-    let mut first_cell = true;
-
-    let mut is_add_heading_to_contents = false;
-    let mut heading_content_string: Option<String> = None;
-
-    for event in parser {
-        match event {
-            Event::Start(Tag::Table(alignments)) => {
-                // 构建列格式
-                let column_format = alignments
-                    .iter()
-                    .map(|align| match align {
-                        pulldown_cmark::Alignment::Left => r#">{\raggedright\arraybackslash}X"#,
-                        pulldown_cmark::Alignment::Center => r#">{\centering\arraybackslash}X"#,
-                        pulldown_cmark::Alignment::Right => r#">{\raggedleft\arraybackslash}X"#,
-                        _ => r#">{\centering\arraybackslash}X"#, // fallback
-                    })
-                    .map(|s| format!("|{}", s))
-                    .collect::<String>()
-                    + "|";
-
-                let text = format!(
-                    "\\begin{{tabularx}}{{\\textwidth}}{{{}}} \\hline\n",
-                    column_format
-                );
-                output.push_str(&text);
-            }
-            Event::End(Tag::Table(_)) => {
-                output.push_str("\\end{tabularx}\n\n");
-            }
-            Event::Start(Tag::TableHead) => {
-                _inside_header = true;
-                first_cell = true;
-            }
-            Event::End(Tag::TableHead) => {
-                output.push_str(" \\\\ \\hline\n");
-            }
-            Event::Start(Tag::TableRow) => {
-                first_cell = true;
-            }
-            Event::End(Tag::TableRow) => {
-                output.push_str(" \\\\ \\hline\n");
-            }
-            Event::Start(Tag::TableCell) => {
-                if !first_cell {
-                    output.push_str(" & ");
-                }
-                first_cell = false;
-            }
-            Event::End(Tag::TableCell) => {}
-
-            Event::Start(Tag::Heading(level, _, classes)) => {
-                output.push_str("\\");
-                let mut base_string = match level {
-                    HeadingLevel::H1 => "chapter",
-                    HeadingLevel::H2 => "section",
-                    HeadingLevel::H3 => "subsection",
-                    HeadingLevel::H4 => "subsubsection",
-                    HeadingLevel::H5 => "paragraph",
-                    _ => "textbf",
-                }
-                .to_string();
-                if classes.contains(&"unnumbered") {
-                    base_string.push_str("*");
-                }
-                if classes.contains(&"add-contents") {
-                    is_add_heading_to_contents = true;
-                }
-                base_string.push_str("{");
-                output.push_str(&base_string);
-            }
-            Event::End(Tag::Heading(_, _, _)) => {
-                output.push_str("}\n");
-                if is_add_heading_to_contents {
-                    output.push_str(&format!(
-                        "\\addcontentsline{{toc}}{{chapter}}{{{}}}\n",
-                        heading_content_string.as_ref().unwrap()
-                    ));
-                    is_add_heading_to_contents = false;
-                    heading_content_string = None;
-                }
-                output.push_str("\n");
-            }
-            Event::Start(Tag::Paragraph) => {
-                _inside_paragraph = true;
-                // 可选：插入 \par 或开头标记
-                // output.push_str("\\par\n");
-            }
-            Event::End(Tag::Paragraph) => {
-                _inside_paragraph = false;
-                output.push_str("\n\n"); // 两个换行表示段落结束
-            }
-            Event::Text(text) if inside_image => {
-                image_caption.push_str(&text); // 累加文字，避免分段
-            }
-            Event::Text(text) if codeblock_status != CustomCodeBlockKind::None => {
-                output.push_str(&text);
-            }
-            Event::Text(text) => {
-                let replaced = apply_text_replacements(&text, &IN_TEXT_REPLACEMENT_TABLE);
+/// `convert_markdown_to_latex` 的转换结果
+pub struct ConvertedDocument {
+    pub body: String,
+    /// 当 `extract_title` 为真且文档存在一级标题时，这里是提取出的标题文字
+    pub title: Option<String>,
+}
 
-                if is_add_heading_to_contents {
-                    heading_content_string = Some(replaced.to_string())
-                }
-                output.push_str(&replaced);
-            }
-            Event::Start(Tag::Emphasis) => output.push_str("\\textit{"),
-            Event::End(Tag::Emphasis) => output.push('}'),
-            Event::Start(Tag::Strong) => output.push_str("\\textbf{"),
-            Event::End(Tag::Strong) => output.push('}'),
-            Event::Start(Tag::Link(_href, url, _)) => {
-                output.push_str(&format!("\\href{{{}}}{{", url));
-            }
-            Event::End(Tag::Link(_, _, _)) => output.push('}'),
-            Event::Start(Tag::Image(_, url, _)) => {
-                inside_image = true;
-                image_url = url.to_string();
-            }
-            Event::End(Tag::Image(_, _, _)) => {
-                output.push_str("\\begin{figure}[htbp]\n");
-                output.push_str(&format!(
-                    "\\centering\n\\includegraphics[width=0.8\\textwidth]{{{}}}\n",
-                    image_url
-                ));
-                output.push_str(&format!("\\caption{{{}}}\n", image_caption));
-                output.push_str(&format!("\\label{{fig:{}}}\n", image_url));
-                output.push_str("\\end{figure}\n");
-
-                inside_image = false;
-                image_url.clear();
-                image_caption.clear();
-            }
-            Event::Start(Tag::List(Some(_))) => {
-                output.push_str("\\begin{enumerate}\n");
-                _in_ordered_list = true;
-            }
-            Event::Start(Tag::List(None)) => {
-                output.push_str("\\begin{itemize}\n");
-                _in_unordered_list = true;
-            }
-            Event::End(Tag::List(Some(_))) => {
-                output.push_str("\\end{enumerate}\n");
-                _in_ordered_list = false;
-            }
-            Event::End(Tag::List(None)) => {
-                output.push_str("\\end{itemize}\n");
-                _in_unordered_list = false;
-            }
-            Event::Start(Tag::Item) => {
-                output.push_str("\\item ");
-            }
-            Event::End(Tag::Item) => {
-                output.push('\n');
-            }
-            Event::Start(Tag::CodeBlock(kind)) => {
-                codeblock_status = handle_code_block_start(kind, &mut output);
-            }
-            Event::End(Tag::CodeBlock(_)) => {
-                match codeblock_status {
-                    CustomCodeBlockKind::None => {}
-                    CustomCodeBlockKind::Code(_) => {
-                        output.push_str("\\end{lstlisting}\n\n");
-                    }
-                    CustomCodeBlockKind::RawLatex => {}
-                    CustomCodeBlockKind::Equation => {
-                        output.push_str("\\end{equation}\n\n");
-                    }
-                }
+/// 先把预处理过的 Markdown 折叠成 `ast::Node` 树，再交给 `render::render_document`
+/// 走一遍渲染。标签校验与引用标记回填仍然是字符串层面的 preprocess/postprocess。
+fn convert_markdown_to_latex(
+    markdown: &str,
+    highlight: HighlightMode,
+    extract_title: bool,
+) -> Result<ConvertedDocument, ConvertError> {
+    // 交叉引用的 key 在 preprocess 阶段收集，转换结束后用于检测悬空引用
+    let mut referenced_labels: Vec<String> = Vec::new();
+    let preprocessed = preprocess(markdown, &mut referenced_labels)?;
+
+    let nodes = ast::build_ast(&preprocessed);
+    let (mut body, title, defined_labels) = render::render_document(&nodes, highlight, extract_title);
+    let postprocessed = postprocess(&mut body);
+
+    for key in &referenced_labels {
+        if !defined_labels.contains(key) {
+            eprintln!("warning: 交叉引用 `{key}` 没有找到对应的标签定义");
+        }
+    }
 
-                codeblock_status = CustomCodeBlockKind::None;
-            }
-            Event::Code(code) => {
-                // 行内代码可映射为 \texttt{}
-                output.push_str(&format!(
-                    "\\texttt{{{}}}",
-                    apply_text_replacements(&code, &IN_TEXT_REPLACEMENT_TABLE)
-                ));
-            }
-            Event::Rule => output.push_str("\\hrulefill\n"),
-            Event::SoftBreak => {
-                // 对应 Markdown 中的普通换行（段内换行）
-                output.push_str("\n"); // 或者 "\\\\\n" 如果你希望在 LaTeX 中也表现为换行
-            }
-            Event::HardBreak => {
-                // 对应 Markdown 中以空格+换行表示的强制换行（行尾 \ 或空两格）
-                output.push_str("\\\\\n");
-            }
-            _ => {}
+    Ok(ConvertedDocument {
+        body: postprocessed,
+        title,
+    })
+}
+
+/// 将标题文字转换为 `\label{sec:...}` 使用的 slug：转小写，非字母数字折叠为单个 `-`
+fn slugify(text: &str) -> String {
+    let mut slug = String::new();
+    let mut last_was_dash = false;
+    for c in text.chars() {
+        if c.is_alphanumeric() {
+            slug.extend(c.to_lowercase());
+            last_was_dash = false;
+        } else if !slug.is_empty() && !last_was_dash {
+            slug.push('-');
+            last_was_dash = true;
         }
     }
+    while slug.ends_with('-') {
+        slug.pop();
+    }
+    slug
+}
 
-    let postprocessed = postprocess(&mut output);
+/// 将引用块正文包装为普通 `quote`，若首行带有 `[!NOTE]` 风格的 callout 标记则改用 `tcolorbox`
+fn render_blockquote(body: &str) -> String {
+    if let Some(caps) = ADMONITION_RE.captures(body) {
+        let title = titlecase(&caps[1]);
+        let rest = caps[2].trim();
+        format!(
+            "\\begin{{tcolorbox}}[title={}]\n{}\n\\end{{tcolorbox}}\n\n",
+            title, rest
+        )
+    } else {
+        format!("\\begin{{quote}}\n{}\n\\end{{quote}}\n\n", body)
+    }
+}
 
-    postprocessed
+/// 首字母大写，其余小写（`NOTE` -> `Note`）
+fn titlecase(s: &str) -> String {
+    let mut chars = s.chars();
+    match chars.next() {
+        None => String::new(),
+        Some(first) => first.to_uppercase().collect::<String>() + &chars.as_str().to_lowercase(),
+    }
 }
 
-fn preprocess(input: &str) -> String {
-    // let input = apply_text_replacements(input, &PRE_REPLACEMENT_TABLE);
+fn preprocess(input: &str, referenced_labels: &mut Vec<String>) -> Result<String, ConvertError> {
     let mut output = String::new();
     let mut lines = input.lines().peekable();
 
@@ -322,13 +430,70 @@ fn preprocess(input: &str) -> String {
                     output.push('\n');
                 }
             }
+        } else if trimmed.starts_with("```") {
+            // 普通围栏代码块：原样透传，不对其内容做 `` [`key`] ``/`[*key*]` 引用
+            // 标记替换，否则代码示例里出现的引用语法会被误改写，校验失败时还会
+            // 让整篇文档的转换直接报错
+            output.push_str(line);
+            output.push('\n');
+
+            while let Some(next_line) = lines.peek() {
+                if next_line.trim() == "```" {
+                    output.push_str(lines.next().unwrap());
+                    output.push('\n');
+                    break;
+                } else {
+                    output.push_str(lines.next().unwrap());
+                    output.push('\n');
+                }
+            }
         } else {
-            output.push_str(&apply_text_replacements(&line, &PRE_REPLACEMENT_TABLE));
+            output.push_str(&replace_ref_spans(line, referenced_labels)?);
             output.push('\n');
         }
     }
 
-    output
+    Ok(output)
+}
+
+/// 解析一行中的 `` [`key`] `` / `[*key*]` 引用标记，校验 key 后替换为回填标记，
+/// 真正的 `\cite{}`/`\ref{}` 文本在 `postprocess` 阶段写出，避免被当作普通正文转义
+fn replace_ref_spans(
+    line: &str,
+    referenced_labels: &mut Vec<String>,
+) -> Result<String, ConvertError> {
+    let with_citations = replace_marked_spans(line, &CITATION_RE, "citation", 'C', None)?;
+    replace_marked_spans(
+        &with_citations,
+        &REFERENCE_RE,
+        "reference",
+        'R',
+        Some(referenced_labels),
+    )
+}
+
+fn replace_marked_spans(
+    line: &str,
+    re: &Regex,
+    kind: &'static str,
+    tag: char,
+    mut collect: Option<&mut Vec<String>>,
+) -> Result<String, ConvertError> {
+    let mut output = String::with_capacity(line.len());
+    let mut last_end = 0;
+    for caps in re.captures_iter(line) {
+        let whole = caps.get(0).unwrap();
+        let key = &caps[1];
+        validate_key(kind, key)?;
+        if let Some(labels) = collect.as_deref_mut() {
+            labels.push(key.to_string());
+        }
+        output.push_str(&line[last_end..whole.start()]);
+        output.push_str(&format!("\u{E000}{tag}{key}\u{E000}"));
+        last_end = whole.end();
+    }
+    output.push_str(&line[last_end..]);
+    Ok(output)
 }
 
 /// 对纯文本做替换
@@ -348,51 +513,70 @@ fn apply_text_replacements_inversedly(text: &str, table: &HashMap<&str, &str>) -
     replaced
 }
 
-fn handle_code_block_start<'a>(
-    kind: CodeBlockKind<'a>,
-    output: &mut String,
-) -> CustomCodeBlockKind<'a> {
-    if let CodeBlockKind::Fenced(ref lang) = kind {
-        let str = lang.clone();
-        let tags = str
-            .split(" ")
-            .filter(|it| !it.is_empty())
-            .collect::<Vec<_>>();
-        for tag in tags.iter() {
-            // Equation Matching
-            let reg = Regex::new(r"block_equation\{(.*?)\}").unwrap();
-            for caps in reg.captures_iter(tag) {
-                output.push_str("\\begin{equation}\n");
-                let name = &caps[1];
-                if !name.is_empty() {
-                    output.push_str(&format!("\\label{{eq:{}}}\n", name));
-                }
-                return CustomCodeBlockKind::Equation;
-            }
+/// 使用 syntect 对代码逐词染色，产出可直接插入 `alltt` 环境的 LaTeX 片段
+fn highlight_with_syntect(code: &str, lang: Option<&str>) -> String {
+    use syntect::easy::HighlightLines;
+    use syntect::highlighting::ThemeSet;
+    use syntect::parsing::SyntaxSet;
+    use syntect::util::LinesWithEndings;
+
+    let syntax_set = SyntaxSet::load_defaults_newlines();
+    let theme_set = ThemeSet::load_defaults();
+    let syntax = lang
+        .and_then(|l| syntax_set.find_syntax_by_token(l))
+        .or_else(|| lang.and_then(|l| syntax_set.find_syntax_by_name(l)))
+        .unwrap_or_else(|| syntax_set.find_syntax_plain_text());
+    let theme = &theme_set.themes["InspiredGitHub"];
+    let mut highlighter = HighlightLines::new(syntax, theme);
+
+    let mut result = String::new();
+    for line in LinesWithEndings::from(code) {
+        let ranges = highlighter
+            .highlight_line(line, &syntax_set)
+            .unwrap_or_default();
+        for (style, text) in ranges {
+            if text.is_empty() {
+                continue;
+            }
+            let color = format!(
+                "{:02X}{:02X}{:02X}",
+                style.foreground.r, style.foreground.g, style.foreground.b
+            );
+            result.push_str(&format!(
+                "\\textcolor[HTML]{{{}}}{{{}}}",
+                color,
+                escape_for_alltt(text)
+            ));
         }
-        if tags.contains(&"latex") && tags.contains(&"raw") {
-            CustomCodeBlockKind::RawLatex
-        } else {
-            output.push_str("\\begin{lstlisting}\n");
-            CustomCodeBlockKind::Code(Some(lang.clone()))
+    }
+    result
+}
+
+/// 转义 `alltt` 环境中仍然有特殊含义的字符（反斜杠与花括号）
+fn escape_for_alltt(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\textbackslash{}"),
+            '{' => escaped.push_str("\\{"),
+            '}' => escaped.push_str("\\}"),
+            _ => escaped.push(c),
         }
-    } else {
-        CustomCodeBlockKind::Code(None)
     }
+    escaped
 }
 
+/// 回填引用标记（`\cite{}`/`\ref{}`）并还原被转义的公式内容
 fn postprocess(input: &mut String) -> String {
-    let re = Regex::new(r#"\\cite\\\{(.*?)\\\}"#).unwrap();
-    let result = re.replace_all(input, r"\cite{$1}");
-
-    let re = Regex::new(r#"\\ref\\\{(.*?)\\\}"#).unwrap();
-    let result = re.replace_all(&result, r"\ref{$1}");
-
-    let result = inversedly_replace(&result, r"\$([^\$\n]+)\$", |it| format!("${}$", it));
-    let result = inversedly_replace(&result, r"\\ref\{([^}]+)\}", |it| {
-        format!("\\ref{{{}}}", it)
+    let result = REF_MARKER_RE.replace_all(input, |caps: &regex::Captures| {
+        let key = &caps[2];
+        match &caps[1] {
+            "C" => format!("\\cite{{{}}}", key),
+            _ => format!("\\ref{{{}}}", key),
+        }
     });
-    result.to_string()
+
+    inversedly_replace(&result, r"\$([^\$\n]+)\$", |it| format!("${}$", it)).to_string()
 }
 
 fn inversedly_replace<'a>(
@@ -407,3 +591,100 @@ fn inversedly_replace<'a>(
         formater(&rp)
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_key_accepts_alphanumeric_dash_colon() {
+        assert!(validate_key("citation", "smith-2020").is_ok());
+        assert!(validate_key("reference", "sec:intro").is_ok());
+    }
+
+    #[test]
+    fn validate_key_rejects_empty() {
+        assert!(matches!(
+            validate_key("citation", ""),
+            Err(ConvertError::EmptyKey { kind: "citation" })
+        ));
+    }
+
+    #[test]
+    fn validate_key_rejects_whitespace_and_punctuation() {
+        assert!(matches!(
+            validate_key("citation", "a b"),
+            Err(ConvertError::InvalidChar { offending_char: ' ', .. })
+        ));
+        assert!(matches!(
+            validate_key("reference", "a.b"),
+            Err(ConvertError::InvalidChar { offending_char: '.', .. })
+        ));
+    }
+
+    #[test]
+    fn preprocess_skips_ref_span_rewriting_inside_fenced_code_blocks() {
+        let mut referenced = Vec::new();
+        let input = "```text\nSee [`smith-2020`] for details.\n```\n";
+        let out = preprocess(input, &mut referenced).unwrap();
+        assert!(out.contains("See [`smith-2020`] for details."));
+        assert!(referenced.is_empty());
+    }
+
+    #[test]
+    fn preprocess_does_not_fail_on_invalid_key_inside_fenced_code_blocks() {
+        let mut referenced = Vec::new();
+        let input = "```text\nSee [`a b`] for details.\n```\n";
+        assert!(preprocess(input, &mut referenced).is_ok());
+    }
+
+    #[test]
+    fn preprocess_still_validates_ref_spans_outside_code_blocks() {
+        let mut referenced = Vec::new();
+        let input = "See [`a b`] for details.\n";
+        assert!(preprocess(input, &mut referenced).is_err());
+    }
+
+    #[test]
+    fn slugify_lowercases_and_collapses_non_alphanumeric() {
+        assert_eq!(slugify("Data & Analysis"), "data-analysis");
+        assert_eq!(slugify("Rust_and_You!!"), "rust-and-you");
+    }
+
+    #[test]
+    fn slugify_trims_trailing_dashes() {
+        assert_eq!(slugify("Trailing punctuation..."), "trailing-punctuation");
+    }
+
+    #[test]
+    fn extract_front_matter_parses_title_and_author() {
+        let md = "---\ntitle: My Doc\nauthor: \"Jane Doe\"\n---\nbody text\n";
+        let (title, author, body) = extract_front_matter(md);
+        assert_eq!(title.as_deref(), Some("My Doc"));
+        assert_eq!(author.as_deref(), Some("Jane Doe"));
+        assert_eq!(body, "body text\n");
+    }
+
+    #[test]
+    fn extract_front_matter_passes_through_when_absent() {
+        let md = "# Heading\nbody text\n";
+        let (title, author, body) = extract_front_matter(md);
+        assert_eq!(title, None);
+        assert_eq!(author, None);
+        assert_eq!(body, md);
+    }
+
+    #[test]
+    fn render_blockquote_wraps_plain_quote() {
+        let out = render_blockquote("just a quote");
+        assert!(out.starts_with("\\begin{quote}"));
+        assert!(out.contains("just a quote"));
+    }
+
+    #[test]
+    fn render_blockquote_detects_admonition_marker() {
+        let out = render_blockquote("[!WARNING]\nbe careful");
+        assert!(out.contains("\\begin{tcolorbox}[title=Warning]"));
+        assert!(out.contains("be careful"));
+    }
+}
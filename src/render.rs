@@ -0,0 +1,380 @@
+//! 把 `ast::Node` 树渲染为 LaTeX 正文。与 `ast` 模块分离后，这里只关心“怎么写
+//! 出 LaTeX”，不再需要关心事件流的配对/缓冲问题。
+
+use crate::ast::Node;
+use crate::HighlightMode;
+use pulldown_cmark::{Alignment, HeadingLevel};
+use std::collections::{HashMap, HashSet};
+
+/// 单个代码块最终采用的渲染方式
+enum CodeRenderMode {
+    /// 纯 `lstlisting`，不附带 `language=`
+    Plain,
+    /// `lstlisting`，附带 `listings` 认识的语言名
+    Listings,
+    /// 逐词上色，写入 `alltt` 环境
+    Syntect,
+}
+
+struct RenderCtx {
+    highlight: HighlightMode,
+    /// 渲染过程中产生的标签（标题、公式、图片），供悬空引用检测使用
+    defined_labels: HashSet<String>,
+    /// label -> 渲染后的正文，脚注引用处直接内联 `\footnote{}`。
+    /// 注意：同一个 label 被引用多次时，每处引用都会各自内联一份相同正文的
+    /// `\footnote{}`，产生独立编号，不会像 `\footnotemark`/`\footnotetext`
+    /// 那样共享同一个脚注编号——重复引用是少数情形，暂不实现编号去重
+    footnote_definitions: HashMap<String, String>,
+}
+
+/// 渲染整棵文档树。`extract_title` 为真时，第一个一级标题不写入正文，而是作为
+/// 返回值中的标题文字。返回值为 (正文, 标题, 渲染中收集到的标签集合)。
+pub fn render_document(
+    nodes: &[Node],
+    highlight: HighlightMode,
+    extract_title: bool,
+) -> (String, Option<String>, HashSet<String>) {
+    let mut ctx = RenderCtx {
+        highlight,
+        defined_labels: HashSet::new(),
+        footnote_definitions: collect_footnote_definitions(nodes, highlight),
+    };
+
+    let mut body = String::new();
+    let mut title = None;
+    for node in nodes {
+        if extract_title && title.is_none() {
+            if let Node::Heading {
+                level: HeadingLevel::H1,
+                children,
+                ..
+            } = node
+            {
+                // 与标题正文的 \label/\addcontentsline 一致，标题文字也要做 LaTeX 转义，
+                // 否则 `# Data & Analysis` 这类标题会产出裸 `\title{Data & Analysis}`
+                title = Some(flatten_escaped(children));
+                continue;
+            }
+        }
+        render_node(node, &mut body, &mut ctx);
+    }
+
+    (body, title, ctx.defined_labels)
+}
+
+/// 脚注定义只会出现在文档的顶层（与段落同级），提前渲染一遍缓存下来，
+/// 这样引用处无论在定义之前还是之后出现都能直接内联 `\footnote{}`
+fn collect_footnote_definitions(nodes: &[Node], highlight: HighlightMode) -> HashMap<String, String> {
+    let mut definitions = HashMap::new();
+    for node in nodes {
+        if let Node::FootnoteDefinition { label, children } = node {
+            let mut scratch = RenderCtx {
+                highlight,
+                defined_labels: HashSet::new(),
+                footnote_definitions: HashMap::new(),
+            };
+            let mut body = String::new();
+            render_children(children, &mut body, &mut scratch);
+            definitions.insert(label.clone(), body.trim().to_string());
+        }
+    }
+    definitions
+}
+
+fn render_children(nodes: &[Node], out: &mut String, ctx: &mut RenderCtx) {
+    for node in nodes {
+        render_node(node, out, ctx);
+    }
+}
+
+fn render_children_to_string(nodes: &[Node], ctx: &mut RenderCtx) -> String {
+    let mut out = String::new();
+    render_children(nodes, &mut out, ctx);
+    out
+}
+
+fn render_node(node: &Node, out: &mut String, ctx: &mut RenderCtx) {
+    match node {
+        Node::Heading {
+            level,
+            classes,
+            children,
+        } => render_heading(*level, classes, children, out, ctx),
+        Node::Paragraph(children) => {
+            render_children(children, out, ctx);
+            out.push_str("\n\n");
+        }
+        Node::List { ordered, items } => render_list(*ordered, items, out, ctx),
+        Node::Table { alignments, rows } => render_table(alignments, rows, out, ctx),
+        Node::CodeBlock { lang, body } => render_code_block(lang.as_deref(), body, ctx.highlight, out),
+        Node::Equation { label, body } => {
+            out.push_str("\\begin{equation}\n");
+            if let Some(label) = label {
+                out.push_str(&format!("\\label{{eq:{}}}\n", label));
+                ctx.defined_labels.insert(format!("eq:{}", label));
+            }
+            out.push_str(body);
+            out.push_str("\\end{equation}\n\n");
+        }
+        Node::RawLatex { body } => out.push_str(body),
+        Node::Image { url, caption } => {
+            out.push_str("\\begin{figure}[htbp]\n");
+            out.push_str(&format!(
+                "\\centering\n\\includegraphics[width=0.8\\textwidth]{{{}}}\n",
+                url
+            ));
+            out.push_str(&format!("\\caption{{{}}}\n", flatten_plain(caption)));
+            out.push_str(&format!("\\label{{fig:{}}}\n", url));
+            ctx.defined_labels.insert(format!("fig:{}", url));
+            out.push_str("\\end{figure}\n");
+        }
+        Node::BlockQuote(children) => {
+            let body = render_children_to_string(children, ctx);
+            out.push_str(&crate::render_blockquote(body.trim()));
+        }
+        Node::FootnoteDefinition { .. } => {
+            // 已在 collect_footnote_definitions 里渲染并缓存，正文中跳过
+        }
+        Node::FootnoteReference(label) => match ctx.footnote_definitions.get(label) {
+            Some(body) => out.push_str(&format!("\\footnote{{{}}}", body)),
+            None => eprintln!("warning: 脚注引用 `{label}` 没有找到对应的定义"),
+        },
+        Node::Link { url, children } => {
+            out.push_str(&format!("\\href{{{}}}{{", url));
+            render_children(children, out, ctx);
+            out.push('}');
+        }
+        Node::Emphasis(children) => {
+            out.push_str("\\textit{");
+            render_children(children, out, ctx);
+            out.push('}');
+        }
+        Node::Strong(children) => {
+            out.push_str("\\textbf{");
+            render_children(children, out, ctx);
+            out.push('}');
+        }
+        Node::Strikethrough(children) => {
+            out.push_str("\\sout{");
+            render_children(children, out, ctx);
+            out.push('}');
+        }
+        Node::Text(text) => {
+            out.push_str(&crate::apply_text_replacements(text, &crate::IN_TEXT_REPLACEMENT_TABLE))
+        }
+        Node::Code(code) => out.push_str(&format!(
+            "\\texttt{{{}}}",
+            crate::apply_text_replacements(code, &crate::IN_TEXT_REPLACEMENT_TABLE)
+        )),
+        Node::Rule => out.push_str("\\hrulefill\n"),
+        Node::SoftBreak => out.push('\n'),
+        Node::HardBreak => out.push_str("\\\\\n"),
+        // 只会出现在各自的容器节点里，由对应的 render_* 直接处理
+        Node::ListItem { .. } | Node::TableRow(_) | Node::TableCell(_) | Node::TaskMarker(_) => {}
+    }
+}
+
+fn render_heading(
+    level: HeadingLevel,
+    classes: &[String],
+    children: &[Node],
+    out: &mut String,
+    ctx: &mut RenderCtx,
+) {
+    let mut command = match level {
+        HeadingLevel::H1 => "chapter",
+        HeadingLevel::H2 => "section",
+        HeadingLevel::H3 => "subsection",
+        HeadingLevel::H4 => "subsubsection",
+        HeadingLevel::H5 => "paragraph",
+        _ => "textbf",
+    }
+    .to_string();
+    let unnumbered = classes.iter().any(|c| c == "unnumbered");
+    let add_to_contents = classes.iter().any(|c| c == "add-contents");
+    if unnumbered {
+        command.push('*');
+    }
+
+    out.push('\\');
+    out.push_str(&command);
+    out.push('{');
+    render_children(children, out, ctx);
+    out.push_str("}\n");
+
+    let plain_text = flatten_escaped(children);
+    if add_to_contents {
+        out.push_str(&format!(
+            "\\addcontentsline{{toc}}{{chapter}}{{{}}}\n",
+            plain_text
+        ));
+    }
+    if level != HeadingLevel::H6 {
+        let slug = crate::slugify(&plain_text);
+        if !slug.is_empty() {
+            out.push_str(&format!("\\label{{sec:{}}}\n", slug));
+            ctx.defined_labels.insert(format!("sec:{}", slug));
+        }
+    }
+    out.push('\n');
+}
+
+fn render_list(ordered: bool, items: &[Node], out: &mut String, ctx: &mut RenderCtx) {
+    let env = if ordered { "enumerate" } else { "itemize" };
+    out.push_str(&format!("\\begin{{{}}}\n", env));
+    for item in items {
+        if let Node::ListItem { task, children } = item {
+            out.push_str("\\item ");
+            if let Some(checked) = task {
+                let marker = if *checked { "$\\boxtimes$" } else { "$\\square$" };
+                out.push_str(marker);
+                out.push(' ');
+            }
+            render_children(children, out, ctx);
+            out.push('\n');
+        }
+    }
+    out.push_str(&format!("\\end{{{}}}\n", env));
+}
+
+fn render_table(alignments: &[Alignment], rows: &[Node], out: &mut String, ctx: &mut RenderCtx) {
+    let column_format = alignments
+        .iter()
+        .map(|align| match align {
+            Alignment::Left => r#">{\raggedright\arraybackslash}X"#,
+            Alignment::Center => r#">{\centering\arraybackslash}X"#,
+            Alignment::Right => r#">{\raggedleft\arraybackslash}X"#,
+            _ => r#">{\centering\arraybackslash}X"#,
+        })
+        .map(|s| format!("|{}", s))
+        .collect::<String>()
+        + "|";
+
+    out.push_str(&format!(
+        "\\begin{{tabularx}}{{\\textwidth}}{{{}}} \\hline\n",
+        column_format
+    ));
+    for row in rows {
+        let Node::TableRow(cells) = row else { continue };
+        for (i, cell) in cells.iter().enumerate() {
+            if i > 0 {
+                out.push_str(" & ");
+            }
+            if let Node::TableCell(children) = cell {
+                render_children(children, out, ctx);
+            }
+        }
+        out.push_str(" \\\\ \\hline\n");
+    }
+    out.push_str("\\end{tabularx}\n\n");
+}
+
+/// 根据围栏语言标记与 `--highlight` 选项决定渲染方式，并写出完整的代码块
+fn render_code_block(lang: Option<&str>, body: &str, highlight: HighlightMode, out: &mut String) {
+    let lang_token = lang.map(|l| l.trim().to_string());
+    let known_listings = lang_token
+        .as_deref()
+        .and_then(|l| crate::LISTINGS_LANGUAGES.get(l.to_lowercase().as_str()));
+
+    let mode = match (highlight, known_listings, &lang_token) {
+        (HighlightMode::None, ..) => CodeRenderMode::Plain,
+        (HighlightMode::Listings, Some(_), _) => CodeRenderMode::Listings,
+        (HighlightMode::Listings, None, Some(l)) if !l.is_empty() => CodeRenderMode::Syntect,
+        (HighlightMode::Listings, None, _) => CodeRenderMode::Plain,
+        (HighlightMode::Syntect, ..) => CodeRenderMode::Syntect,
+    };
+
+    match mode {
+        CodeRenderMode::Plain => {
+            out.push_str("\\begin{lstlisting}\n");
+            out.push_str(body);
+            out.push_str("\\end{lstlisting}\n\n");
+        }
+        CodeRenderMode::Listings => {
+            let canonical = known_listings.unwrap();
+            out.push_str(&format!("\\begin{{lstlisting}}[language={}]\n", canonical));
+            out.push_str(body);
+            out.push_str("\\end{lstlisting}\n\n");
+        }
+        CodeRenderMode::Syntect => {
+            out.push_str("\\begin{alltt}\n");
+            out.push_str(&crate::highlight_with_syntect(body, lang_token.as_deref()));
+            out.push_str("\\end{alltt}\n\n");
+        }
+    }
+}
+
+/// 提取节点列表中的纯文本，保留嵌套的强调/链接文字，但不转义（用于图片说明）
+fn flatten_plain(nodes: &[Node]) -> String {
+    let mut s = String::new();
+    for node in nodes {
+        match node {
+            Node::Text(text) => s.push_str(text),
+            Node::Emphasis(children)
+            | Node::Strong(children)
+            | Node::Strikethrough(children)
+            | Node::Link { children, .. } => s.push_str(&flatten_plain(children)),
+            _ => {}
+        }
+    }
+    s
+}
+
+/// 与 `flatten_plain` 相同，但对文本做 LaTeX 转义（用于标题提取、`\label`/`\addcontentsline`）
+fn flatten_escaped(nodes: &[Node]) -> String {
+    let mut s = String::new();
+    for node in nodes {
+        match node {
+            Node::Text(text) => {
+                s.push_str(&crate::apply_text_replacements(text, &crate::IN_TEXT_REPLACEMENT_TABLE))
+            }
+            Node::Emphasis(children)
+            | Node::Strong(children)
+            | Node::Strikethrough(children)
+            | Node::Link { children, .. } => s.push_str(&flatten_escaped(children)),
+            _ => {}
+        }
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_code_block_highlight_none_always_plain() {
+        let mut out = String::new();
+        render_code_block(Some("rust"), "fn main() {}", HighlightMode::None, &mut out);
+        assert!(out.starts_with("\\begin{lstlisting}\n"));
+        assert!(!out.contains("language="));
+    }
+
+    #[test]
+    fn render_code_block_listings_known_language_uses_language_option() {
+        let mut out = String::new();
+        render_code_block(Some("python"), "print(1)", HighlightMode::Listings, &mut out);
+        assert!(out.contains("\\begin{lstlisting}[language=Python]"));
+    }
+
+    #[test]
+    fn render_code_block_listings_unknown_language_falls_back_to_syntect() {
+        let mut out = String::new();
+        render_code_block(Some("brainfuck"), "++++", HighlightMode::Listings, &mut out);
+        assert!(out.starts_with("\\begin{alltt}\n"));
+    }
+
+    #[test]
+    fn render_code_block_listings_no_language_is_plain() {
+        let mut out = String::new();
+        render_code_block(None, "no lang", HighlightMode::Listings, &mut out);
+        assert!(out.starts_with("\\begin{lstlisting}\n"));
+    }
+
+    #[test]
+    fn render_code_block_syntect_mode_always_uses_alltt() {
+        let mut out = String::new();
+        render_code_block(Some("python"), "print(1)", HighlightMode::Syntect, &mut out);
+        assert!(out.starts_with("\\begin{alltt}\n"));
+    }
+}